@@ -2,6 +2,9 @@ use crate::future::Future;
 use crate::loom::sync::Arc;
 use crate::runtime::scheduler::multi_thread::worker;
 use crate::runtime::{blocking, driver, task::{self, JoinHandle}, OnChildTaskSpawnContext, OnTopLevelTaskSpawnContext, TaskHookHarness, TaskHookHarnessFactory};
+use crate::runtime::task_hooks::FinalizerDriver;
+#[cfg(tokio_unstable)]
+use crate::runtime::task_hooks::FinalizerQueue;
 use crate::util::RngSeedGenerator;
 
 use crate::runtime::task::Schedule;
@@ -30,6 +33,12 @@ pub(crate) struct Handle {
     /// User-supplied hooks to invoke for things
     #[cfg(tokio_unstable)]
     pub(crate) task_hooks: Option<Arc<dyn TaskHookHarnessFactory + Send + Sync + 'static>>,
+
+    /// Finalizer sub-tasks registered via `on_task_terminate`, pending for this
+    /// scheduler to schedule. Shared by every task this scheduler owns -- see
+    /// [`FinalizerQueue`].
+    #[cfg(tokio_unstable)]
+    pub(crate) finalizers: FinalizerQueue,
 }
 
 impl Handle {
@@ -51,6 +60,29 @@ impl Handle {
         self.close();
     }
 
+    /// Drains this scheduler's [`FinalizerQueue`], scheduling every registered
+    /// finalizer to run as an ordinary (untracked) task.
+    ///
+    /// Called right after a terminating task's `on_task_terminate` hook runs, so that
+    /// its finalizers are guaranteed to be scheduled even once the task's `JoinHandle`
+    /// has been detached. This only schedules finalizers -- it does not wait for them
+    /// -- so they run concurrently with, not strictly before, the teardown of whichever
+    /// task triggered the drain. `finalizers` is shared by every task this scheduler
+    /// owns, so a drain can end up scheduling another task's finalizers too if they
+    /// were registered first. Binds each finalizer directly, bypassing
+    /// `bind_new_task`'s harness lookup entirely -- a finalizer is cleanup the harness
+    /// itself emitted, not application code that should be re-instrumented.
+    #[cfg(tokio_unstable)]
+    pub(crate) fn drain_finalizers(me: &Arc<Self>) {
+        for finalizer in me.finalizers.drain() {
+            let id = task::Id::next();
+            let finalizer = crate::runtime::task_hooks::WithCurrentTaskId::new(finalizer, id);
+            let (handle, notified) = me.shared.owned.bind(finalizer, me.clone(), id);
+            me.schedule_option_task_without_yield(notified);
+            drop(handle);
+        }
+    }
+
     pub(super) fn bind_new_task<T>(
         me: &Arc<Self>,
         future: T,
@@ -77,8 +109,16 @@ impl Handle {
             }
         };
 
+        #[cfg(tokio_unstable)]
+        let finalizers: Option<Arc<dyn FinalizerDriver>> = hooks
+            .is_some()
+            .then(|| Arc::new(HandleFinalizerDriver(me.clone())) as Arc<dyn FinalizerDriver>);
+        #[cfg(not(tokio_unstable))]
+        let finalizers: Option<Arc<dyn FinalizerDriver>> = None;
+
+        let future = crate::runtime::task_hooks::HookedTask::new(future, hooks, finalizers);
+        let future = crate::runtime::task_hooks::WithCurrentTaskId::new(future, id);
 
-        
         let (handle, notified) = me.shared.owned.bind(future, me.clone(), id);
 
         me.schedule_option_task_without_yield(notified);
@@ -97,6 +137,25 @@ cfg_unstable! {
     }
 }
 
+/// [`FinalizerDriver`] for the multi thread scheduler, handed to a [`HookedTask`] so it
+/// can push into and drain `Handle::finalizers` without `task_hooks` depending on this
+/// scheduler directly.
+///
+/// [`HookedTask`]: crate::runtime::task_hooks::HookedTask
+#[cfg(tokio_unstable)]
+struct HandleFinalizerDriver(Arc<Handle>);
+
+#[cfg(tokio_unstable)]
+impl FinalizerDriver for HandleFinalizerDriver {
+    fn queue(&self) -> &FinalizerQueue {
+        &self.0.finalizers
+    }
+
+    fn drain(&self) {
+        Handle::drain_finalizers(&self.0);
+    }
+}
+
 impl fmt::Debug for Handle {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("multi_thread::Handle { ... }").finish()
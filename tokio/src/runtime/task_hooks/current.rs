@@ -0,0 +1,184 @@
+use super::{task, TaskState};
+use std::any::Any;
+use std::cell::Cell;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::ptr::NonNull;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+
+thread_local! {
+    /// The `task::Id` of the task currently being polled on this worker thread, if any.
+    ///
+    /// Set for the duration of a task's `poll` (including re-entrant polls, via the
+    /// save/restore behavior of [`CurrentTaskGuard`]) so that synchronous code running
+    /// inside the task can identify its owning task without the ID being threaded
+    /// through every call.
+    static CURRENT_TASK_ID: Cell<Option<task::Id>> = const { Cell::new(None) };
+}
+
+/// RAII guard which restores the previous "current task" id on drop.
+///
+/// Restoring on drop (rather than after a successful poll) ensures the thread-local
+/// is left correct even if the polled future panics or this guard is dropped as part
+/// of unwinding.
+pub(crate) struct CurrentTaskGuard {
+    prev: Option<task::Id>,
+}
+
+impl Drop for CurrentTaskGuard {
+    fn drop(&mut self) {
+        CURRENT_TASK_ID.with(|cell| cell.set(self.prev));
+    }
+}
+
+/// Installs `id` as the current task for the duration of the returned guard.
+fn set_current_task_id(id: task::Id) -> CurrentTaskGuard {
+    let prev = CURRENT_TASK_ID.with(|cell| cell.replace(Some(id)));
+    CurrentTaskGuard { prev }
+}
+
+/// Returns the [`task::Id`] of the task currently being polled on this thread, if any.
+///
+/// Returns `None` outside of a task poll, e.g. on a thread that isn't currently
+/// driving a task. Re-exported as `tokio::task::try_id`.
+#[cfg_attr(not(tokio_unstable), allow(unreachable_pub))]
+pub fn try_id() -> Option<task::Id> {
+    CURRENT_TASK_ID.with(|cell| cell.get())
+}
+
+thread_local! {
+    /// A pointer to the [`TaskState`] of the task currently being polled on this
+    /// worker thread, if any. Borrowed from the [`HookedTask`] wrapper for the
+    /// duration of a single poll; never outlives the guard that installed it.
+    ///
+    /// [`HookedTask`]: super::HookedTask
+    static CURRENT_TASK_STATE: Cell<Option<NonNull<TaskState>>> = const { Cell::new(None) };
+}
+
+/// RAII guard which restores the previous "current task state" pointer on drop.
+///
+/// Carries the lifetime of the `&TaskState` it was built from, so a guard can't be
+/// stashed (in a field, a static, another thread-local) past the point where that
+/// `TaskState` is guaranteed to still be alive -- the borrow checker rejects it instead
+/// of leaving it as an invariant callers have to maintain by hand.
+pub(crate) struct CurrentTaskStateGuard<'a> {
+    prev: Option<NonNull<TaskState>>,
+    _marker: PhantomData<&'a TaskState>,
+}
+
+impl Drop for CurrentTaskStateGuard<'_> {
+    fn drop(&mut self) {
+        CURRENT_TASK_STATE.with(|cell| cell.set(self.prev));
+    }
+}
+
+/// Installs `state` as the current task's state for the duration of the returned guard.
+pub(crate) fn set_current_task_state(state: &TaskState) -> CurrentTaskStateGuard<'_> {
+    let prev = CURRENT_TASK_STATE.with(|cell| cell.replace(Some(NonNull::from(state))));
+    CurrentTaskStateGuard {
+        prev,
+        _marker: PhantomData,
+    }
+}
+
+/// Looks up the current task (see [`try_id`]) and, if a harness attached a value of
+/// type `T` to it, yields a reference to that value.
+///
+/// Returns `None` if there is no current task, or if no `T` was attached to it.
+/// Re-exported as `tokio::task::with_state`.
+#[cfg_attr(not(tokio_unstable), allow(unreachable_pub))]
+pub fn with_state<T, F, R>(f: F) -> Option<R>
+where
+    T: Any + Send + Sync,
+    F: FnOnce(&T) -> R,
+{
+    let ptr = CURRENT_TASK_STATE.with(|cell| cell.get())?;
+    // SAFETY: `ptr` was derived from a `&TaskState` that a `CurrentTaskStateGuard<'a>` still
+    // held live on the polling thread's stack -- the guard's `'a` ties it to that borrow, so
+    // it can't outlive the `TaskState` it points at, and its `Drop` restores the thread-local's
+    // previous value (a still-live outer state, or `None`) before the `TaskState` itself can
+    // be dropped. So for as long as this thread-local holds `Some`, the pointee is valid. We only ever hand out a reference with a lifetime scoped
+    // to this function call, never the pointer itself.
+    let state = unsafe { ptr.as_ref() };
+    state.get::<T>().map(f)
+}
+
+pin_project! {
+    /// Wraps a task's future so that [`try_id`] reports this task's `id` for the
+    /// duration of each poll.
+    pub(crate) struct WithCurrentTaskId<T> {
+        #[pin]
+        inner: T,
+        id: task::Id,
+    }
+}
+
+impl<T> WithCurrentTaskId<T> {
+    pub(crate) fn new(inner: T, id: task::Id) -> Self {
+        Self { inner, id }
+    }
+}
+
+impl<T: Future> Future for WithCurrentTaskId<T> {
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let _guard = set_current_task_id(*this.id);
+        this.inner.poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw()
+        }
+        fn noop(_: *const ()) {}
+        fn raw() -> RawWaker {
+            RawWaker::new(std::ptr::null(), &RawWakerVTable::new(clone, noop, noop, noop))
+        }
+        unsafe { Waker::from_raw(raw()) }
+    }
+
+    #[test]
+    fn try_id_is_none_outside_a_poll() {
+        assert_eq!(try_id(), None);
+    }
+
+    #[test]
+    fn try_id_reports_the_polling_task_and_restores_on_drop() {
+        let outer_id = task::Id::next();
+        let inner_id = task::Id::next();
+
+        let task = WithCurrentTaskId::new(
+            async move {
+                assert_eq!(try_id(), Some(outer_id));
+
+                // A nested poll (e.g. a child task driven inline) must see its own id
+                // while it runs and leave the outer id restored once it returns -- the
+                // save/restore behavior `CURRENT_TASK_ID`'s doc comment promises.
+                let mut nested = Box::pin(WithCurrentTaskId::new(std::future::ready(()), inner_id));
+                let nested_waker = noop_waker();
+                let mut nested_cx = Context::from_waker(&nested_waker);
+                assert_eq!(nested.as_mut().poll(&mut nested_cx), Poll::Ready(()));
+
+                assert_eq!(try_id(), Some(outer_id));
+            },
+            outer_id,
+        );
+        let mut task = Box::pin(task);
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(()));
+        assert_eq!(try_id(), None);
+    }
+}
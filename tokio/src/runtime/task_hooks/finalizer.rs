@@ -0,0 +1,61 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::loom::sync::Mutex;
+
+type BoxedFinalizer = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A per-scheduler queue of finalizer sub-tasks registered from `on_task_terminate`.
+///
+/// Every task owned by a given scheduler shares one `FinalizerQueue` -- it is not
+/// scoped to the individual task that registered a finalizer. The scheduler drains it
+/// -- scheduling each pending finalizer to run as its own task -- once some task's
+/// body finishes; if two tasks terminate around the same time, whichever drains first
+/// ends up scheduling the other's finalizers too. Nothing registered is lost or run
+/// twice, but a drain only *schedules* finalizers, it does not wait for them, so they
+/// run concurrently with (not strictly before) the teardown of the task that
+/// triggered it.
+///
+/// [`on_task_terminate`]: super::TaskHookHarness::on_task_terminate
+#[derive(Default)]
+pub(crate) struct FinalizerQueue {
+    pending: Mutex<Vec<BoxedFinalizer>>,
+}
+
+impl FinalizerQueue {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `fut` to be scheduled as its own task the next time this queue is
+    /// drained.
+    pub(crate) fn push(&self, fut: BoxedFinalizer) {
+        self.pending.lock().push(fut);
+    }
+
+    /// Takes every finalizer registered so far, leaving the queue empty.
+    ///
+    /// Callers are expected to schedule each of these as its own task -- there is no
+    /// guarantee they run, or complete, before this call returns.
+    pub(crate) fn drain(&self) -> Vec<BoxedFinalizer> {
+        std::mem::take(&mut *self.pending.lock())
+    }
+}
+
+/// Capability, supplied by the scheduler that owns a task, to drain that scheduler's
+/// [`FinalizerQueue`] and schedule any registered finalizers to run.
+///
+/// A [`HookedTask`] holds one of these (as `Arc<dyn FinalizerDriver>`) so that it can
+/// trigger a drain right after `on_task_terminate` runs, without `task_hooks` needing
+/// to know which scheduler it's running under.
+///
+/// [`HookedTask`]: super::HookedTask
+pub(crate) trait FinalizerDriver: Send + Sync {
+    /// The queue that `on_task_terminate` pushes into via
+    /// [`OnTaskTerminateContext`](super::OnTaskTerminateContext).
+    fn queue(&self) -> &FinalizerQueue;
+
+    /// Drains `queue()`, scheduling every finalizer registered so far to run as its
+    /// own task. Does not wait for them to complete.
+    fn drain(&self);
+}
@@ -1,15 +1,28 @@
 use super::task;
+use std::future::Future;
 use std::marker::PhantomData;
 
+mod current;
+mod finalizer;
+mod poll;
+mod state;
+
+pub use current::{try_id, with_state};
+pub(crate) use current::WithCurrentTaskId;
+pub(crate) use finalizer::{FinalizerDriver, FinalizerQueue};
+pub(crate) use poll::HookedTask;
+pub use state::TaskState;
+
+/// A [`Box`]ed harness together with the [`TaskState`] its factory attached to the task.
+pub(crate) type NewHarness = (Box<dyn TaskHookHarness + Send + Sync + 'static>, TaskState);
+
 /// A factory which produces new [`TaskHookHarness`] objects for tasks which either have been
 /// spawned in "detached mode" via the builder, or which were spawned from outside the runtime or
 /// from another context where no [`TaskHookHarness`] was present.
 pub trait TaskHookHarnessFactory {
-    /// Create a new [`TaskHookHarness`] object which the runtime will attach to a given task.
-    fn on_top_level_spawn(
-        &self,
-        ctx: &mut OnTopLevelTaskSpawnContext<'_>,
-    ) -> Option<Box<dyn TaskHookHarness + Send + Sync + 'static>>;
+    /// Create a new [`TaskHookHarness`] object which the runtime will attach to a given task,
+    /// along with the [`TaskState`] that task should start out with.
+    fn on_top_level_spawn(&self, ctx: &mut OnTopLevelTaskSpawnContext<'_>) -> Option<NewHarness>;
 }
 
 /// Trait for user-provided "harness" objects which are attached to tasks and provide hook
@@ -19,16 +32,14 @@ pub trait TaskHookHarness {
     fn before_poll(&mut self, ctx: &mut BeforeTaskPollContext<'_>);
 
     /// Post-poll task hook which runs arbitrary user logic.
-    fn after_poll(&mut self, ctx: &mut BeforeTaskPollContext<'_>);
+    fn after_poll(&mut self, ctx: &mut AfterTaskPollContext<'_>);
 
     /// Task hook which runs when this task spawns a child, unless that child is explicitly spawned
     /// detached from the parent.
     ///
-    /// This hook creates a harness for the child, or detaches the child from any instrumentation.
-    fn on_child_spawn(
-        &mut self,
-        ctx: &mut OnChildTaskSpawnContext<'_>,
-    ) -> Option<Box<dyn TaskHookHarness + Send + Sync + 'static>>;
+    /// This hook creates a harness and [`TaskState`] for the child -- typically by cloning or
+    /// deriving from this task's own state -- or detaches the child from any instrumentation.
+    fn on_child_spawn(&mut self, ctx: &mut OnChildTaskSpawnContext<'_>) -> Option<NewHarness>;
 
     /// Task hook which runs on task termination.
     fn on_task_terminate(&mut self, ctx: &mut OnTaskTerminateContext<'_>);
@@ -65,7 +76,21 @@ impl<'a> OnChildTaskSpawnContext<'a> {
 #[allow(missing_debug_implementations)]
 #[cfg_attr(not(tokio_unstable), allow(unreachable_pub))]
 pub struct OnTaskTerminateContext<'a> {
-    pub(crate) _phantom: PhantomData<&'a ()>,
+    pub(crate) queue: &'a FinalizerQueue,
+}
+
+impl<'a> OnTaskTerminateContext<'a> {
+    /// Registers `fut` as a finalizer sub-task: asynchronous cleanup the runtime
+    /// schedules to run as its own task once this task's body has finished.
+    ///
+    /// This only schedules `fut` -- it is not awaited here, and the scheduler does not
+    /// wait for it before releasing this task's resources, so it runs concurrently with
+    /// (not strictly before) that teardown. Unlike the rest of `on_task_terminate`, which
+    /// is synchronous, `fut` may await -- useful for flushing buffered telemetry,
+    /// releasing leased connections, or emitting a completion event asynchronously.
+    pub fn spawn_finalizer(&mut self, fut: impl Future<Output = ()> + Send + 'static) {
+        self.queue.push(Box::pin(fut));
+    }
 }
 
 #[allow(missing_debug_implementations)]
@@ -77,5 +102,27 @@ pub struct BeforeTaskPollContext<'a> {
 #[allow(missing_debug_implementations)]
 #[cfg_attr(not(tokio_unstable), allow(unreachable_pub))]
 pub struct AfterTaskPollContext<'a> {
+    pub(crate) ready: bool,
+    #[cfg(tokio_unstable)]
+    pub(crate) elapsed: Option<std::time::Duration>,
     pub(crate) _phantom: PhantomData<&'a ()>,
 }
+
+impl<'a> AfterTaskPollContext<'a> {
+    /// Returns `true` if this poll returned `Poll::Ready`, `false` if it returned
+    /// `Poll::Pending`.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Returns the wall-clock duration of this single poll.
+    ///
+    /// Only available when built with `tokio_unstable`, since capturing an [`Instant`]
+    /// around every poll has a (small) cost that we don't want to pay on stable builds.
+    ///
+    /// [`Instant`]: std::time::Instant
+    #[cfg(tokio_unstable)]
+    pub fn elapsed(&self) -> Option<std::time::Duration> {
+        self.elapsed
+    }
+}
@@ -0,0 +1,353 @@
+use super::current::set_current_task_state;
+use super::{
+    AfterTaskPollContext, BeforeTaskPollContext, FinalizerDriver, NewHarness,
+    OnTaskTerminateContext,
+};
+use crate::loom::sync::Arc;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// Wraps a task's future so that, when a harness is attached, `before_poll` and
+    /// `after_poll` run around each inner poll, the harness's [`TaskState`] is
+    /// readable (via `tokio::task::with_state`) for the duration of that poll, and,
+    /// when a [`FinalizerDriver`] was supplied, `on_task_terminate` runs -- with any
+    /// finalizers it registers scheduled to run -- once the inner future resolves.
+    /// Without a `FinalizerDriver`, `on_task_terminate` is not called.
+    ///
+    /// When no harness is attached this is a transparent passthrough: the `Instant` used to
+    /// time `after_poll` is never captured, so there's no cost on the hot path for tasks
+    /// that aren't instrumented.
+    ///
+    /// [`TaskState`]: super::TaskState
+    pub(crate) struct HookedTask<T> {
+        #[pin]
+        inner: T,
+        hooks: Option<NewHarness>,
+        finalizers: Option<Arc<dyn FinalizerDriver>>,
+    }
+}
+
+impl<T> HookedTask<T> {
+    pub(crate) fn new(
+        inner: T,
+        hooks: Option<NewHarness>,
+        finalizers: Option<Arc<dyn FinalizerDriver>>,
+    ) -> Self {
+        Self {
+            inner,
+            hooks,
+            finalizers,
+        }
+    }
+}
+
+impl<T: Future> Future for HookedTask<T> {
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let Some((harness, state)) = this.hooks else {
+            return this.inner.poll(cx);
+        };
+
+        let _state_guard = set_current_task_state(state);
+
+        harness.before_poll(&mut BeforeTaskPollContext {
+            _phantom: PhantomData,
+        });
+
+        #[cfg(tokio_unstable)]
+        let start = Some(std::time::Instant::now());
+
+        let poll = this.inner.poll(cx);
+
+        harness.after_poll(&mut AfterTaskPollContext {
+            ready: poll.is_ready(),
+            #[cfg(tokio_unstable)]
+            elapsed: start.map(|start| start.elapsed()),
+            _phantom: PhantomData,
+        });
+
+        if poll.is_ready() {
+            if let Some(finalizers) = this.finalizers {
+                harness.on_task_terminate(&mut OnTaskTerminateContext {
+                    queue: finalizers.queue(),
+                });
+                finalizers.drain();
+            }
+        }
+
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::task;
+    use crate::runtime::task_hooks::current::{try_id, with_state, WithCurrentTaskId};
+    use crate::runtime::task_hooks::{
+        FinalizerQueue, OnChildTaskSpawnContext, TaskHookHarness, TaskState,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw()
+        }
+        fn noop(_: *const ()) {}
+        fn raw() -> RawWaker {
+            RawWaker::new(std::ptr::null(), &RawWakerVTable::new(clone, noop, noop, noop))
+        }
+        unsafe { Waker::from_raw(raw()) }
+    }
+
+    #[derive(Default)]
+    struct RecordedCalls {
+        before_polls: usize,
+        after_polls: usize,
+        last_ready: bool,
+        terminated: usize,
+        before_poll_id: Option<task::Id>,
+        after_poll_id: Option<task::Id>,
+        terminate_id: Option<task::Id>,
+    }
+
+    struct RecordingHarness {
+        record: Arc<Mutex<RecordedCalls>>,
+    }
+
+    impl TaskHookHarness for RecordingHarness {
+        fn before_poll(&mut self, _ctx: &mut BeforeTaskPollContext<'_>) {
+            let mut record = self.record.lock().unwrap();
+            record.before_polls += 1;
+            record.before_poll_id = try_id();
+        }
+
+        fn after_poll(&mut self, ctx: &mut AfterTaskPollContext<'_>) {
+            let mut record = self.record.lock().unwrap();
+            record.after_polls += 1;
+            record.last_ready = ctx.is_ready();
+            record.after_poll_id = try_id();
+        }
+
+        fn on_child_spawn(&mut self, _ctx: &mut OnChildTaskSpawnContext<'_>) -> Option<NewHarness> {
+            None
+        }
+
+        fn on_task_terminate(&mut self, ctx: &mut OnTaskTerminateContext<'_>) {
+            {
+                let mut record = self.record.lock().unwrap();
+                record.terminated += 1;
+                record.terminate_id = try_id();
+            }
+            ctx.spawn_finalizer(async {});
+        }
+    }
+
+    #[derive(Default)]
+    struct TestFinalizerDriver {
+        queue: FinalizerQueue,
+        drains: AtomicUsize,
+    }
+
+    impl FinalizerDriver for TestFinalizerDriver {
+        fn queue(&self) -> &FinalizerQueue {
+            &self.queue
+        }
+
+        fn drain(&self) {
+            self.drains.fetch_add(1, Ordering::SeqCst);
+            let waker = noop_waker();
+            let mut cx = Context::from_waker(&waker);
+            for mut finalizer in self.queue.drain() {
+                while finalizer.as_mut().poll(&mut cx).is_pending() {}
+            }
+        }
+    }
+
+    #[test]
+    fn instruments_poll_and_drains_finalizers_on_completion() {
+        let id = task::Id::next();
+        let mut state = TaskState::new();
+        state.insert(42i32);
+
+        let observed_id = Arc::new(Mutex::new(None));
+        let observed_state = Arc::new(Mutex::new(None));
+        let (o_id, o_state) = (observed_id.clone(), observed_state.clone());
+        let inner = async move {
+            *o_id.lock().unwrap() = try_id();
+            *o_state.lock().unwrap() = with_state::<i32, _, _>(|v| *v);
+            7
+        };
+
+        let record = Arc::new(Mutex::new(RecordedCalls::default()));
+        let harness: Box<dyn TaskHookHarness + Send + Sync> = Box::new(RecordingHarness {
+            record: record.clone(),
+        });
+        let finalizer_driver = Arc::new(TestFinalizerDriver::default());
+
+        let task = HookedTask::new(
+            inner,
+            Some((harness, state)),
+            Some(finalizer_driver.clone() as Arc<dyn FinalizerDriver>),
+        );
+        let task = WithCurrentTaskId::new(task, id);
+        let mut task = Box::pin(task);
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert_eq!(try_id(), None);
+        let poll = task.as_mut().poll(&mut cx);
+
+        assert_eq!(poll, Poll::Ready(7));
+        assert_eq!(*observed_id.lock().unwrap(), Some(id));
+        assert_eq!(*observed_state.lock().unwrap(), Some(42));
+        assert_eq!(try_id(), None, "guard must restore the previous task on drop");
+        assert_eq!(finalizer_driver.drains.load(Ordering::SeqCst), 1);
+
+        let record = record.lock().unwrap();
+        assert_eq!(record.before_polls, 1);
+        assert_eq!(record.after_polls, 1);
+        assert!(record.last_ready);
+        assert_eq!(record.terminated, 1);
+
+        // `WithCurrentTaskId` must wrap `HookedTask`, not the other way around, or these
+        // hooks -- which is exactly where a tracing/logging harness would call
+        // `try_id()` to tag its output -- would see no current task at all.
+        assert_eq!(record.before_poll_id, Some(id));
+        assert_eq!(record.after_poll_id, Some(id));
+        assert_eq!(record.terminate_id, Some(id));
+    }
+
+    #[test]
+    fn instruments_pending_polls_without_terminating() {
+        let id = task::Id::next();
+        let state = TaskState::new();
+
+        let record = Arc::new(Mutex::new(RecordedCalls::default()));
+        let harness: Box<dyn TaskHookHarness + Send + Sync> = Box::new(RecordingHarness {
+            record: record.clone(),
+        });
+        let finalizer_driver = Arc::new(TestFinalizerDriver::default());
+
+        let task = HookedTask::new(
+            std::future::pending::<()>(),
+            Some((harness, state)),
+            Some(finalizer_driver.clone() as Arc<dyn FinalizerDriver>),
+        );
+        let task = WithCurrentTaskId::new(task, id);
+        let mut task = Box::pin(task);
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(try_id(), None, "guard must restore the previous task on drop");
+
+        let record = record.lock().unwrap();
+        assert_eq!(record.before_polls, 1);
+        assert_eq!(record.after_polls, 1);
+        assert!(!record.last_ready, "is_ready() must be false on a Pending poll");
+        assert_eq!(
+            record.terminated, 0,
+            "on_task_terminate must not run while the task is still pending"
+        );
+        assert_eq!(finalizer_driver.drains.load(Ordering::SeqCst), 0);
+    }
+
+    struct NoopHarness;
+
+    impl TaskHookHarness for NoopHarness {
+        fn before_poll(&mut self, _ctx: &mut BeforeTaskPollContext<'_>) {}
+
+        fn after_poll(&mut self, _ctx: &mut AfterTaskPollContext<'_>) {}
+
+        fn on_child_spawn(&mut self, _ctx: &mut OnChildTaskSpawnContext<'_>) -> Option<NewHarness> {
+            None
+        }
+
+        fn on_task_terminate(&mut self, _ctx: &mut OnTaskTerminateContext<'_>) {}
+    }
+
+    #[test]
+    fn with_state_returns_none_for_an_unattached_type() {
+        let id = task::Id::next();
+        let state = TaskState::new(); // nothing inserted
+
+        let observed = Arc::new(Mutex::new(Some(0i32)));
+        let o = observed.clone();
+        let inner = async move {
+            *o.lock().unwrap() = with_state::<i32, _, _>(|v| *v);
+        };
+
+        let harness: Box<dyn TaskHookHarness + Send + Sync> = Box::new(NoopHarness);
+        let task = HookedTask::new(inner, Some((harness, state)), None);
+        let task = WithCurrentTaskId::new(task, id);
+        let mut task = Box::pin(task);
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(()));
+
+        assert_eq!(*observed.lock().unwrap(), None);
+    }
+
+    /// A harness that derives a child's [`TaskState`] from its own field, the way a real
+    /// harness would propagate context (e.g. a trace span id) down a spawn tree.
+    struct DerivingHarness {
+        child_value: i32,
+    }
+
+    impl TaskHookHarness for DerivingHarness {
+        fn before_poll(&mut self, _ctx: &mut BeforeTaskPollContext<'_>) {}
+
+        fn after_poll(&mut self, _ctx: &mut AfterTaskPollContext<'_>) {}
+
+        fn on_child_spawn(&mut self, _ctx: &mut OnChildTaskSpawnContext<'_>) -> Option<NewHarness> {
+            let mut child_state = TaskState::new();
+            child_state.insert(self.child_value);
+            Some((Box::new(NoopHarness), child_state))
+        }
+
+        fn on_task_terminate(&mut self, _ctx: &mut OnTaskTerminateContext<'_>) {}
+    }
+
+    #[test]
+    fn on_child_spawn_derived_state_is_readable_from_the_child_task() {
+        let child_id = task::Id::next();
+
+        let mut parent = DerivingHarness { child_value: 99 };
+        let derived = parent
+            .on_child_spawn(&mut OnChildTaskSpawnContext {
+                id: child_id,
+                _phantom: PhantomData,
+            })
+            .expect("DerivingHarness always derives a child harness");
+
+        let observed = Arc::new(Mutex::new(None));
+        let o = observed.clone();
+        let inner = async move {
+            *o.lock().unwrap() = with_state::<i32, _, _>(|v| *v);
+        };
+
+        let task = HookedTask::new(inner, Some(derived), None);
+        let task = WithCurrentTaskId::new(task, child_id);
+        let mut task = Box::pin(task);
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(()));
+
+        assert_eq!(*observed.lock().unwrap(), Some(99));
+    }
+}
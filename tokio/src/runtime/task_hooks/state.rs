@@ -0,0 +1,33 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// An opaque, typed per-task state store.
+///
+/// A [`TaskHookHarnessFactory`] or [`TaskHookHarness`] populates this at spawn time
+/// (`on_top_level_spawn`/`on_child_spawn`), and the running task reads it back via
+/// `tokio::task::with_state`.
+///
+/// [`TaskHookHarnessFactory`]: super::TaskHookHarnessFactory
+/// [`TaskHookHarness`]: super::TaskHookHarness
+#[derive(Default)]
+#[allow(missing_debug_implementations)]
+pub struct TaskState {
+    slots: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl TaskState {
+    /// Creates an empty state store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches `value` to this task, overwriting any previous value of type `T`.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) {
+        self.slots.insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    /// Returns the value of type `T` attached to this task, if any.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.slots.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+}